@@ -0,0 +1,120 @@
+//! Exact rational durations.
+//!
+//! Triplets (`1/12`) and dotted notes (`3/16`) don't round-trip cleanly
+//! through `f32`, and accumulating them across a long piece drifts. `Dur`
+//! represents a musical duration as a fraction of a whole note, so summing
+//! and scaling stay exact all the way down to the final tick count.
+
+use std::cmp::Ordering;
+use std::iter::Sum;
+use std::ops::Add;
+
+/// A duration expressed as an exact fraction of a whole note.
+///
+/// Fields are private and only ever set via `Dur::new`/`Dur::ZERO`, so every
+/// `Dur` is guaranteed reduced to lowest terms --- required for the
+/// `#[derive]`d `PartialEq`/`Eq` (structural field comparison) to agree with
+/// `Ord` (cross-multiplication), since an unreduced `Dur` would satisfy one
+/// and not the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dur {
+    num: u32,
+    den: u32,
+}
+
+impl Dur {
+    /// Zero-length duration, the identity for `Sequential` summation.
+    pub const ZERO: Dur = Dur { num: 0, den: 1 };
+
+    /// Builds a new `Dur`, reduced to lowest terms.
+    ///
+    /// A zero denominator is clamped to 1 rather than propagated, so a
+    /// malformed `Dur` can never reach `to_ticks` and divide by zero.
+    pub fn new(num: u32, den: u32) -> Dur {
+        let den = den.max(1);
+        let g = gcd(num, den);
+        Dur { num: num / g, den: den / g }
+    }
+
+    /// Converts to ticks given `ticks_per_whole`, the number of ticks a
+    /// whole note spans. Exact as long as `ticks_per_whole` is divisible by
+    /// `self.den` (true for `MIDI_TEMPO` = 3840 with denominators such as
+    /// 12 or 16); otherwise the result is truncated toward zero.
+    pub fn to_ticks(self, ticks_per_whole: u32) -> u32 {
+        (self.num * ticks_per_whole) / self.den
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.num as f32 / self.den as f32
+    }
+
+    /// The reduced numerator.
+    pub fn numerator(self) -> u32 {
+        self.num
+    }
+
+    /// The reduced denominator.
+    pub fn denominator(self) -> u32 {
+        self.den
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a.max(1) } else { gcd(b, a % b) }
+}
+
+impl Add for Dur {
+    type Output = Dur;
+
+    fn add(self, other: Dur) -> Dur {
+        Dur::new(self.num * other.den + other.num * self.den, self.den * other.den)
+    }
+}
+
+impl PartialOrd for Dur {
+    fn partial_cmp(&self, other: &Dur) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Dur {
+    fn cmp(&self, other: &Dur) -> Ordering {
+        let lhs = self.num as u64 * other.den as u64;
+        let rhs = other.num as u64 * self.den as u64;
+        lhs.cmp(&rhs)
+    }
+}
+
+impl Sum for Dur {
+    fn sum<I: Iterator<Item = Dur>>(iter: I) -> Dur {
+        iter.fold(Dur::ZERO, Add::add)
+    }
+}
+
+/// Convenience conversion so call sites can keep writing `f32` literals
+/// like `1.0 / 12.0` or `3.0 / 16.0`.
+impl From<f32> for Dur {
+    fn from(value: f32) -> Dur {
+        // Denominator large enough to recover exact triplets (/12),
+        // sixteenths (/16) and everything coarser without rounding error.
+        const RESOLUTION: u32 = 1920;
+        Dur::new((value * RESOLUTION as f32).round() as u32, RESOLUTION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_clamps_zero_denominator_instead_of_dividing_by_it() {
+        let dur = Dur::new(3, 0);
+        assert_eq!(dur.denominator(), 1);
+        assert_eq!(dur.to_ticks(3840), dur.numerator() * 3840);
+    }
+
+    #[test]
+    fn new_reduces_to_lowest_terms() {
+        assert_eq!(Dur::new(2, 4), Dur::new(1, 2));
+    }
+}
@@ -0,0 +1,161 @@
+//! The General MIDI instrument set, and the channel-allocation subsystem
+//! that maps instruments onto the 16 physical MIDI channels.
+//!
+//! `Channel` used to conflate "instrument" with "MIDI channel": there are
+//! only 16 real channels, but 128 General MIDI instruments, and channel 10
+//! is conventionally reserved for percussion regardless of program number.
+//! `Instrument` names the patch a `Note` wants to sound like; `UserPatchMap`
+//! is what actually assigns each distinct instrument used in a performance
+//! to a channel, so export can emit correct `0xCn` program changes.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// The full General MIDI instrument set, in program-change order (0-127),
+/// plus `Percussion`, which always lives on MIDI channel 10 regardless of
+/// program number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instrument {
+    AcousticGrandPiano, BrightAcousticPiano, ElectricGrandPiano, HonkyTonkPiano,
+    ElectricPiano1, ElectricPiano2, Harpsichord, Clavinet,
+    Celesta, Glockenspiel, MusicBox, Vibraphone, Marimba, Xylophone, TubularBells, Dulcimer,
+    DrawbarOrgan, PercussiveOrgan, RockOrgan, ChurchOrgan, ReedOrgan, Accordion, Harmonica, TangoAccordion,
+    AcousticGuitarNylon, AcousticGuitarSteel, ElectricGuitarJazz, ElectricGuitarClean,
+    ElectricGuitarMuted, OverdrivenGuitar, DistortionGuitar, GuitarHarmonics,
+    AcousticBass, ElectricBassFinger, ElectricBassPick, FretlessBass,
+    SlapBass1, SlapBass2, SynthBass1, SynthBass2,
+    Violin, Viola, Cello, Contrabass, TremoloStrings, PizzicatoStrings, OrchestralHarp, Timpani,
+    StringEnsemble1, StringEnsemble2, SynthStrings1, SynthStrings2,
+    ChoirAahs, VoiceOohs, SynthVoice, OrchestraHit,
+    Trumpet, Trombone, Tuba, MutedTrumpet, FrenchHorn, BrassSection, SynthBrass1, SynthBrass2,
+    SopranoSax, AltoSax, TenorSax, BaritoneSax, Oboe, EnglishHorn, Bassoon, Clarinet,
+    Piccolo, Flute, Recorder, PanFlute, BlownBottle, Shakuhachi, Whistle, Ocarina,
+    Lead1Square, Lead2Sawtooth, Lead3Calliope, Lead4Chiff, Lead5Charang, Lead6Voice, Lead7Fifths, Lead8BassLead,
+    Pad1NewAge, Pad2Warm, Pad3Polysynth, Pad4Choir, Pad5Bowed, Pad6Metallic, Pad7Halo, Pad8Sweep,
+    Fx1Rain, Fx2Soundtrack, Fx3Crystal, Fx4Atmosphere, Fx5Brightness, Fx6Goblins, Fx7Echoes, Fx8SciFi,
+    Sitar, Banjo, Shamisen, Koto, Kalimba, Bagpipe, Fiddle, Shanai,
+    TinkleBell, Agogo, SteelDrums, Woodblock, TaikoDrum, MelodicTom, SynthDrum, ReverseCymbal,
+    GuitarFretNoise, BreathNoise, Seashore, BirdTweet, TelephoneRing, Helicopter, Applause, Gunshot,
+    /// Not a General MIDI program; always routed to channel 10 instead.
+    Percussion,
+}
+
+impl Instrument {
+    /// The GM program-change number (0-127) for this instrument, or `None`
+    /// for `Percussion`, which has no program of its own.
+    pub fn program_number(self) -> Option<u32> {
+        if self == Instrument::Percussion {
+            None
+        } else {
+            Some(self as u32)
+        }
+    }
+
+    pub fn is_percussion(self) -> bool {
+        self == Instrument::Percussion
+    }
+}
+
+/// The 0-based MIDI channel reserved for percussion (channel 10, 1-based).
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// Everything that can go wrong assigning instruments to channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// There is no melodic channel left to assign, and round-robin reuse
+    /// is still not possible (e.g. zero melodic channels are available).
+    NoChannelsAvailable,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::NoChannelsAvailable => write!(f, "no MIDI channel available to assign"),
+        }
+    }
+}
+
+/// Assigns each distinct `Instrument` used in a performance to one of the
+/// 16 MIDI channels. Channel 10 is reserved for `Percussion`; once all 15
+/// remaining channels are taken, further instruments round-robin onto
+/// already-assigned channels.
+#[derive(Debug, Default)]
+pub struct UserPatchMap {
+    assignments: HashMap<Instrument, u8>,
+    next_slot: usize,
+}
+
+impl UserPatchMap {
+    pub fn new() -> UserPatchMap {
+        UserPatchMap::default()
+    }
+
+    /// The melodic channels, in allocation order (every channel except the
+    /// one reserved for percussion).
+    fn melodic_channels() -> impl Iterator<Item = u8> {
+        (0u8..16).filter(|&channel| channel != PERCUSSION_CHANNEL)
+    }
+
+    /// Returns the channel already assigned to `instrument`, or assigns it
+    /// one (round-robining over the melodic channels once they run out).
+    pub fn channel_for(&mut self, instrument: Instrument) -> Result<u8, PatchError> {
+        if instrument.is_percussion() {
+            return Ok(PERCUSSION_CHANNEL);
+        }
+        if let Some(&channel) = self.assignments.get(&instrument) {
+            return Ok(channel);
+        }
+
+        let channels: Vec<u8> = UserPatchMap::melodic_channels().collect();
+        if channels.is_empty() {
+            // Unreachable today: `melodic_channels` always yields the fixed
+            // 15-channel range. Kept as a real error rather than an
+            // assertion so a future change to channel selection (e.g.
+            // reserving more channels) fails safely instead of panicking.
+            return Err(PatchError::NoChannelsAvailable);
+        }
+        let channel = channels[self.next_slot % channels.len()];
+        self.next_slot += 1;
+        self.assignments.insert(instrument, channel);
+        Ok(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percussion_always_gets_channel_nine() {
+        let mut patch_map = UserPatchMap::new();
+        assert_eq!(patch_map.channel_for(Instrument::Percussion).unwrap(), PERCUSSION_CHANNEL);
+    }
+
+    #[test]
+    fn round_robins_once_melodic_channels_run_out() {
+        const MELODIC: &[Instrument] = &[
+            Instrument::AcousticGrandPiano, Instrument::BrightAcousticPiano, Instrument::ElectricGrandPiano,
+            Instrument::HonkyTonkPiano, Instrument::ElectricPiano1, Instrument::ElectricPiano2,
+            Instrument::Harpsichord, Instrument::Clavinet, Instrument::Celesta, Instrument::Glockenspiel,
+            Instrument::MusicBox, Instrument::Vibraphone, Instrument::Marimba, Instrument::Xylophone,
+            Instrument::TubularBells, Instrument::Dulcimer, Instrument::DrawbarOrgan,
+        ];
+        assert_eq!(MELODIC.len(), 17, "need more than the 15 available melodic channels");
+
+        let mut patch_map = UserPatchMap::new();
+        let channels: Vec<u8> = MELODIC.iter().map(|&instrument| patch_map.channel_for(instrument).unwrap()).collect();
+
+        assert!(channels.iter().all(|&c| c != PERCUSSION_CHANNEL));
+        assert_eq!(channels[..15].iter().collect::<std::collections::HashSet<_>>().len(), 15);
+        assert_eq!(channels[15], channels[0]);
+        assert_eq!(channels[16], channels[1]);
+    }
+
+    #[test]
+    fn repeated_lookups_of_the_same_instrument_return_the_same_channel() {
+        let mut patch_map = UserPatchMap::new();
+        let first = patch_map.channel_for(Instrument::Flute).unwrap();
+        let second = patch_map.channel_for(Instrument::Flute).unwrap();
+        assert_eq!(first, second);
+    }
+}
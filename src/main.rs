@@ -1,11 +1,27 @@
 #![allow(dead_code, unused_macros)]
 
+// modules
+mod duration;
+mod instrument;
+mod midi;
+mod output;
+mod parser;
+mod performance;
+mod phrase;
+
 // imports
 use std::fmt;
 
+use duration::Dur;
+use instrument::Instrument;
+use midi::MidiFile;
+use output::{Adsr, Sinus};
+use performance::{Event, Performance};
+use phrase::{Loudness, Phrase, PhraseAttribute};
+
 // constants
-/// The duration of a whole note in MIDI ticks.
-const MIDI_TEMPO: f32 = 3840.0;
+/// The duration of a whole note, in MIDI ticks.
+const MIDI_TEMPO: u32 = 3840;
 
 // traits
 
@@ -23,11 +39,14 @@ impl<T> Cloneable for T where T: 'static + MusicElement + Clone {
 
 /// All `MusicElement`s need the ability to output a duration and set the channel retroactively
 trait MusicElement : fmt::Debug + Cloneable {
-    /// Returns the musical duration of a `MusicElement`
-    /// Durations are implemented as `f32` due to the fractional nature of music.
-    fn duration(&self) -> f32;
+    /// Returns the musical duration of a `MusicElement`, as an exact
+    /// fraction of a whole note.
+    fn duration(&self) -> Dur;
     /// Sets the channel to something else.
-    fn set_channel(&mut self, channel: Channel);
+    fn set_channel(&mut self, channel: Instrument);
+    /// Flattens this element into absolute-time events, as if it started
+    /// sounding at tick `start`.
+    fn perform(&self, start: f32) -> Performance;
 }
 
 impl Clone for Box<MusicElement> {
@@ -39,11 +58,72 @@ impl Clone for Box<MusicElement> {
 // enums
 #[derive(Debug, Clone)]
 /// Specifies the different note names available
-/// TODO: implement From<String> on this, to allow things like "Do".into() or "C#".into()
 enum NoteClass {
     C, Cs, D, Ds, E, F, Fs, G, Gs, A, As, B
 }
 
+impl NoteClass {
+    /// Parses a note name, accepting sharp/flat letter names (`"C#"`,
+    /// `"Db"`) and solfège syllables (`"Do"`, `"Re"`, ..., case-insensitive,
+    /// optionally with a trailing `#`/`b` accidental). Flats and sharps
+    /// that land on the same pitch class collapse to the same variant,
+    /// since `NoteClass` only spells pitches with sharps.
+    fn parse(input: &str) -> Option<NoteClass> {
+        let lower = input.to_ascii_lowercase();
+
+        const SOLFEGE: &[(&str, i32)] =
+            &[("do", 0), ("re", 2), ("mi", 4), ("fa", 5), ("sol", 7), ("so", 7), ("la", 9), ("ti", 11), ("si", 11)];
+        for &(name, base) in SOLFEGE {
+            if let Some(rest) = lower.strip_prefix(name) {
+                return NoteClass::from_semitone(apply_accidentals(base, rest)?);
+            }
+        }
+
+        let mut chars = lower.chars();
+        let base = match chars.next()? {
+            'c' => 0, 'd' => 2, 'e' => 4, 'f' => 5, 'g' => 7, 'a' => 9, 'b' => 11,
+            _ => return None,
+        };
+        NoteClass::from_semitone(apply_accidentals(base, chars.as_str())?)
+    }
+
+    fn from_semitone(semitone: i32) -> Option<NoteClass> {
+        Some(match semitone {
+            0 => NoteClass::C, 1 => NoteClass::Cs, 2 => NoteClass::D, 3 => NoteClass::Ds,
+            4 => NoteClass::E, 5 => NoteClass::F, 6 => NoteClass::Fs, 7 => NoteClass::G,
+            8 => NoteClass::Gs, 9 => NoteClass::A, 10 => NoteClass::As, 11 => NoteClass::B,
+            _ => return None,
+        })
+    }
+}
+
+/// Applies a run of `#`/`s` (sharp) and `b`/`f` (flat) accidentals to a base
+/// semitone, wrapping into the 0..12 range. Returns `None` if `accidentals`
+/// contains anything other than those four characters.
+fn apply_accidentals(base: i32, accidentals: &str) -> Option<i32> {
+    let mut offset = 0;
+    for c in accidentals.chars() {
+        offset += match c {
+            '#' | 's' => 1,
+            'b' | 'f' => -1,
+            _ => return None,
+        };
+    }
+    Some(((base + offset) % 12 + 12) % 12)
+}
+
+impl<'a> From<&'a str> for NoteClass {
+    fn from(value: &'a str) -> NoteClass {
+        NoteClass::parse(value).unwrap_or_else(|| panic!("invalid note name: {:?}", value))
+    }
+}
+
+impl From<String> for NoteClass {
+    fn from(value: String) -> NoteClass {
+        NoteClass::from(value.as_str())
+    }
+}
+
 impl fmt::Display for NoteClass {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -63,20 +143,6 @@ impl fmt::Display for NoteClass {
     }
 }
 
-#[derive(Debug, Clone)]
-/// Channel lists the available musical channels to play on
-/// This list is the only instruments used in the original project, and thus do not contain the full list of instruments
-enum Channel {
-    Piano, Organ, Guitar, Violin, Flute, Trumpet, Helicopter, Telephone
-}
-
-impl Channel {
-    /// Converts the channel to a number
-    fn to_u32(self) -> u32 {
-        self as u32 + 1
-    }
-}
-
 // structs
 #[derive(Debug)]
 struct MidiNote {
@@ -89,25 +155,36 @@ struct MidiNote {
 struct Note {
     name: NoteClass,
     octave: u32,
-    instrument: Channel,
-    duration: f32,
+    instrument: Instrument,
+    duration: Dur,
+    /// MIDI velocity (0-127), i.e. how hard the note is struck.
+    volume: u8,
 }
 
 impl Note {
-    /// Creates a new note with the instrument set to `Piano` and duration to 1/4 by default.
+    /// Creates a new note with the instrument set to `AcousticGrandPiano`, duration to 1/4 and volume to a
+    /// moderately loud 100 by default.
     fn new(name: NoteClass, octave: u32) -> Note {
-        Note { name, octave, instrument: Channel::Piano, duration: 0.25 }
+        Note { name, octave, instrument: Instrument::AcousticGrandPiano, duration: Dur::new(1, 4), volume: 100 }
     }
 
     /// Builder method that adds a channel to a `Note`
-    fn channel(mut self, instrument: Channel) -> Note {
+    fn channel(mut self, instrument: Instrument) -> Note {
         self.instrument = instrument;
         self
     }
 
-    /// Builder method that adds a duration to a `Note`
-    fn duration(mut self, duration: f32) -> Note {
-        self.duration = duration;
+    /// Builder method that adds a duration to a `Note`.
+    /// Accepts a `Dur` directly, or an `f32` literal like `1.0 / 12.0` for
+    /// existing call sites.
+    fn duration<D: Into<Dur>>(mut self, duration: D) -> Note {
+        self.duration = duration.into();
+        self
+    }
+
+    /// Builder method that sets a `Note`'s velocity
+    fn volume(mut self, volume: u8) -> Note {
+        self.volume = volume;
         self
     }
 
@@ -115,39 +192,55 @@ impl Note {
     fn to_midi(&self) -> MidiNote {
         let offset = self.name.clone() as u32;
         let pitch = (12 * self.octave) + offset;
-        MidiNote { pitch, duration: self.duration() }
+        let duration = self.duration().to_ticks(MIDI_TEMPO) as f32;
+        MidiNote { pitch, duration }
     }
 }
 
 impl MusicElement for Note {
-    fn duration(&self) -> f32 {
-        self.duration * MIDI_TEMPO
+    fn duration(&self) -> Dur {
+        self.duration
     }
 
-    fn set_channel(&mut self, channel: Channel) {
+    fn set_channel(&mut self, channel: Instrument) {
         self.instrument = channel;
     }
+
+    fn perform(&self, start: f32) -> Performance {
+        let midi_note = self.to_midi();
+        vec![Event {
+            start_tick: start,
+            duration: midi_note.duration,
+            pitch: midi_note.pitch,
+            instrument: self.instrument,
+            velocity: self.volume,
+        }]
+    }
 }
 
 #[derive(Debug, Clone)]
 /// A simple pause, only contains a duration
 struct Pause {
-    duration: f32,
+    duration: Dur,
 }
 
 impl Pause {
     /// Initialises a pause with a duration
-    fn new(duration: f32) -> Pause {
-        Pause { duration }
+    fn new<D: Into<Dur>>(duration: D) -> Pause {
+        Pause { duration: duration.into() }
     }
 }
 
 impl MusicElement for Pause {
-    fn duration(&self) -> f32 {
-        self.duration * MIDI_TEMPO
+    fn duration(&self) -> Dur {
+        self.duration
     }
 
-    fn set_channel(&mut self, _: Channel) {} // do nothing
+    fn set_channel(&mut self, _: Instrument) {} // do nothing
+
+    fn perform(&self, _start: f32) -> Performance {
+        Vec::new() // silence produces no events, but still advances time via duration()
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -157,14 +250,24 @@ struct Sequential {
 }
 
 impl MusicElement for Sequential {
-    fn duration(&self) -> f32 {
+    fn duration(&self) -> Dur {
         self.elements.iter().map(|e| e.duration()).sum()
     }
 
-    fn set_channel(&mut self, channel: Channel) {
+    fn set_channel(&mut self, channel: Instrument) {
         for element in self.elements.iter_mut() {
-            element.set_channel(channel.clone());
+            element.set_channel(channel);
+        }
+    }
+
+    fn perform(&self, start: f32) -> Performance {
+        let mut cursor = start;
+        let mut events = Performance::new();
+        for element in self.elements.iter() {
+            events.extend(element.perform(cursor));
+            cursor += element.duration().to_ticks(MIDI_TEMPO) as f32;
         }
+        events
     }
 }
 
@@ -187,15 +290,19 @@ struct Parallel {
 
 impl MusicElement for Parallel {
     // Due to all of the notes appearing in parallel, the overall duration must be that of the longest one
-    fn duration(&self) -> f32 {
-        self.elements.iter().fold(std::f32::NEG_INFINITY, |acc, ref e| e.duration().max(acc))
+    fn duration(&self) -> Dur {
+        self.elements.iter().map(|e| e.duration()).max().unwrap_or(Dur::ZERO)
     }
 
-    fn set_channel(&mut self, channel: Channel) {
+    fn set_channel(&mut self, channel: Instrument) {
         for element in self.elements.iter_mut() {
-            element.set_channel(channel.clone());
+            element.set_channel(channel);
         }
     }
+
+    fn perform(&self, start: f32) -> Performance {
+        self.elements.iter().flat_map(|element| element.perform(start)).collect()
+    }
 }
 
 /// Helper macro for parallel compositions, packs the inputs in a box before moving them into the vector
@@ -233,12 +340,44 @@ fn main() {
     // change the channel to organ and violin for the offset parts of the canon
     let mut music_organ  = music.clone();
     let mut music_violin = music.clone();
-    music_organ.set_channel(Channel::Organ);
-    music_violin.set_channel(Channel::Violin);
+    music_organ.set_channel(Instrument::ChurchOrgan);
+    music_violin.set_channel(Instrument::Violin);
 
-    println!("Length of music: {} bars", music.duration() / MIDI_TEMPO);
+    println!("Length of music: {} bars", music.duration().to_f32());
 
     let canon = parallel![music, sequence![Pause::new(1.0), music_organ], sequence![Pause::new(2.0), music_violin]];
-    println!("Length of canon: {} bars", canon.duration() / MIDI_TEMPO);
+    println!("Length of canon: {} bars", canon.duration().to_f32());
     //println!("Full canon: {:#?}", canon);
+
+    match MidiFile::from_element(&canon) {
+        Ok(midi_file) => {
+            if let Err(e) = midi_file.write_to_file("canon.mid") {
+                eprintln!("Failed to write canon.mid: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to assign MIDI channels: {}", e),
+    }
+
+    // audition the canon as a sine-wave render, so it can be checked without a synth
+    const SAMPLE_RATE: u32 = 44100;
+    let sinus = Sinus { envelope: Adsr::default_for(SAMPLE_RATE) };
+    let samples = output::render(&canon.perform(0.0), &sinus, SAMPLE_RATE);
+    if let Err(e) = output::write_wav("canon.wav", &samples, SAMPLE_RATE) {
+        eprintln!("Failed to write canon.wav: {}", e);
+    }
+
+    // the same opening bar, written as DSL text instead of nested macros
+    match parser::parse("o4 l1/4 c4 c4 c4:3/16 d4:1/16 | e4") {
+        Ok(from_text) => println!("Parsed {} bars from text", from_text.duration().to_f32()),
+        Err(e) => eprintln!("Failed to parse DSL text: {}", e),
+    }
+
+    // a phrase wraps a subtree with dynamics/articulation that only affects
+    // the velocities/durations perform() produces, never the structural duration
+    let forte = Phrase::new(PhraseAttribute::Dynamics(Loudness::FF), Box::new(sequence![c4(0.25), e4(0.25), g4(0.25), c5(0.25)]));
+    let swell = Phrase::new(PhraseAttribute::Crescendo(1.25), Box::new(forte));
+    println!("Phrase duration: {} bars (untouched by the attributes)", swell.duration().to_f32());
+    for event in swell.perform(0.0) {
+        println!("  note at tick {} -> velocity {}", event.start_tick, event.velocity);
+    }
 }
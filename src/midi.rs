@@ -0,0 +1,258 @@
+//! Standard MIDI File (SMF) export.
+//!
+//! Takes the absolute-time `Performance` produced by `MusicElement::perform`
+//! and serialises it as a Format-1 `.mid` file.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::instrument::{PatchError, UserPatchMap};
+use crate::performance::Performance;
+use crate::MusicElement;
+
+/// Ticks per quarter note used as the file's time division.
+/// `MIDI_TEMPO` (a whole note, in ticks) is exactly four of these.
+const DIVISION: u16 = 960;
+
+/// A single absolute-time note on/off event, as produced while walking a
+/// `MusicElement` tree. `program` is only meaningful on `NoteOn`: it is
+/// whichever GM program the note's channel should be set to before it
+/// sounds.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum MidiEvent {
+    NoteOn { tick: f32, channel: u8, program: Option<u32>, key: u32, velocity: u8 },
+    NoteOff { tick: f32, channel: u8, key: u32 },
+}
+
+impl MidiEvent {
+    fn tick(&self) -> f32 {
+        match *self {
+            MidiEvent::NoteOn { tick, .. } => tick,
+            MidiEvent::NoteOff { tick, .. } => tick,
+        }
+    }
+
+    fn channel(&self) -> u8 {
+        match *self {
+            MidiEvent::NoteOn { channel, .. } => channel,
+            MidiEvent::NoteOff { channel, .. } => channel,
+        }
+    }
+}
+
+/// Encodes `value` as a MIDI variable-length quantity: 7 bits per byte,
+/// big-endian, with the high bit set on every byte but the last.
+fn write_vlq(value: u32, out: &mut Vec<u8>) {
+    let mut buffer = value & 0x7f;
+    let mut value = value >> 7;
+    while value > 0 {
+        buffer <<= 8;
+        buffer |= 0x80 | (value & 0x7f);
+        value >>= 7;
+    }
+    loop {
+        out.push((buffer & 0xff) as u8);
+        if buffer & 0x80 == 0 {
+            break;
+        }
+        buffer >>= 8;
+    }
+}
+
+/// A single `MTrk` chunk: a sequence of delta-timed MIDI events.
+#[derive(Debug, Default)]
+pub struct MidiTrack {
+    events: Vec<MidiEvent>,
+}
+
+impl MidiTrack {
+    fn encode(&self) -> Vec<u8> {
+        let mut events = self.events.clone();
+        events.sort_by(|a, b| a.tick().partial_cmp(&b.tick()).unwrap());
+
+        let mut body = Vec::new();
+        let mut last_tick = 0u32;
+        // Tracks the program currently set on each channel, so a change is
+        // only emitted when the instrument actually differs (channels can
+        // be shared by several instruments once `UserPatchMap` round-robins).
+        let mut current_program: [Option<u32>; 16] = [None; 16];
+
+        for event in events {
+            let tick = event.tick().round() as u32;
+            let delta = tick - last_tick;
+            last_tick = tick;
+            let channel = event.channel();
+
+            write_vlq(delta, &mut body);
+            if let MidiEvent::NoteOn { program: Some(program), .. } = event {
+                if current_program[channel as usize] != Some(program) {
+                    current_program[channel as usize] = Some(program);
+                    body.push(0xC0 | channel);
+                    body.push(program as u8);
+                    // the note-on that follows is its own event, at delta 0
+                    write_vlq(0, &mut body);
+                }
+            }
+
+            match event {
+                MidiEvent::NoteOn { key, velocity, .. } => {
+                    body.push(0x90 | channel);
+                    body.push(key as u8);
+                    body.push(velocity);
+                }
+                MidiEvent::NoteOff { key, .. } => {
+                    body.push(0x80 | channel);
+                    body.push(key as u8);
+                    body.push(0);
+                }
+            }
+        }
+
+        // end of track meta event
+        write_vlq(0, &mut body);
+        body.push(0xFF);
+        body.push(0x2F);
+        body.push(0x00);
+
+        let mut chunk = Vec::with_capacity(body.len() + 8);
+        chunk.extend_from_slice(b"MTrk");
+        chunk.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(&body);
+        chunk
+    }
+}
+
+/// A Format-1 Standard MIDI File made up of one or more tracks.
+#[derive(Debug, Default)]
+pub struct MidiFile {
+    tracks: Vec<MidiTrack>,
+}
+
+impl MidiFile {
+    /// Flattens `element` into a single-track `MidiFile`.
+    pub fn from_element(element: &MusicElement) -> Result<MidiFile, PatchError> {
+        MidiFile::from_performance(element.perform(0.0))
+    }
+
+    /// Turns an already-flattened `Performance` into a single-track
+    /// `MidiFile`, splitting each `Event` into its `NoteOn`/`NoteOff` pair
+    /// and assigning each distinct instrument a MIDI channel along the way.
+    pub fn from_performance(performance: Performance) -> Result<MidiFile, PatchError> {
+        let mut patch_map = UserPatchMap::new();
+        let mut events = Vec::with_capacity(performance.len() * 2);
+        for event in performance {
+            let channel = patch_map.channel_for(event.instrument)?;
+            events.push(MidiEvent::NoteOn {
+                tick: event.start_tick,
+                channel,
+                program: event.instrument.program_number(),
+                key: event.pitch,
+                velocity: event.velocity,
+            });
+            events.push(MidiEvent::NoteOff {
+                tick: event.start_tick + event.duration,
+                channel,
+                key: event.pitch,
+            });
+        }
+        Ok(MidiFile { tracks: vec![MidiTrack { events }] })
+    }
+
+    /// Serialises the file to the SMF byte format: a 14-byte `MThd` header
+    /// followed by one `MTrk` chunk per track.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"MThd");
+        out.extend_from_slice(&6u32.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes()); // format 1
+        out.extend_from_slice(&(self.tracks.len() as u16).to_be_bytes());
+        out.extend_from_slice(&DIVISION.to_be_bytes());
+
+        for track in &self.tracks {
+            out.extend_from_slice(&track.encode());
+        }
+        out
+    }
+
+    /// Writes the serialised file out to `path`.
+    pub fn write_to_file(&self, path: &str) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&self.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_vlq_boundaries() {
+        let cases: [(u32, &[u8]); 5] = [
+            (0, &[0x00]),
+            (127, &[0x7F]),
+            (128, &[0x81, 0x00]),
+            (16383, &[0xFF, 0x7F]),
+            (16384, &[0x81, 0x80, 0x00]),
+        ];
+        for &(value, expected) in cases.iter() {
+            let mut out = Vec::new();
+            write_vlq(value, &mut out);
+            assert_eq!(out, expected, "value {}", value);
+        }
+    }
+
+    #[test]
+    fn encode_emits_program_change_note_on_and_note_off() {
+        let track = MidiTrack {
+            events: vec![
+                MidiEvent::NoteOn { tick: 0.0, channel: 0, program: Some(5), key: 60, velocity: 100 },
+                MidiEvent::NoteOff { tick: 480.0, channel: 0, key: 60 },
+            ],
+        };
+        let bytes = track.encode();
+
+        let mut body = Vec::new();
+        write_vlq(0, &mut body);
+        body.push(0xC0);
+        body.push(5);
+        write_vlq(0, &mut body);
+        body.push(0x90);
+        body.push(60);
+        body.push(100);
+        write_vlq(480, &mut body);
+        body.push(0x80);
+        body.push(60);
+        body.push(0);
+        write_vlq(0, &mut body);
+        body.push(0xFF);
+        body.push(0x2F);
+        body.push(0x00);
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        expected.extend_from_slice(&body);
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn from_performance_pairs_each_event_into_note_on_and_note_off() {
+        use crate::performance::Event;
+        use crate::Instrument;
+
+        let performance: Performance = vec![Event {
+            start_tick: 0.0,
+            duration: 480.0,
+            pitch: 60,
+            instrument: Instrument::AcousticGrandPiano,
+            velocity: 100,
+        }];
+
+        let midi_file = MidiFile::from_performance(performance).unwrap();
+        let events = &midi_file.tracks[0].events;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], MidiEvent::NoteOn { tick: 0.0, key: 60, velocity: 100, .. }));
+        assert!(matches!(events[1], MidiEvent::NoteOff { tick: 480.0, key: 60, .. }));
+    }
+}
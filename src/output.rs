@@ -0,0 +1,231 @@
+//! Offline audio rendering.
+//!
+//! Renders a flattened `Performance` directly to PCM samples (and a WAV
+//! file), so a composition can be auditioned without an external synth.
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::performance::Performance;
+use crate::MIDI_TEMPO;
+
+/// Seconds a whole note lasts at the tempo assumed for audio rendering
+/// (120 BPM, i.e. a quarter note is half a second).
+const SECONDS_PER_WHOLE_NOTE: f64 = 2.0;
+
+/// Converts a MIDI key number to a frequency in Hz (A4 = key 69 = 440 Hz).
+fn key_to_frequency(key: u32) -> f64 {
+    440.0 * 2f64.powf((key as f64 - 69.0) / 12.0)
+}
+
+fn ticks_to_secs(ticks: f32) -> f64 {
+    ticks as f64 * SECONDS_PER_WHOLE_NOTE / MIDI_TEMPO as f64
+}
+
+/// A linear attack/decay/sustain/release envelope, expressed in samples.
+#[derive(Debug, Clone, Copy)]
+pub struct Adsr {
+    pub attack: usize,
+    pub decay: usize,
+    pub sustain: f64,
+    pub release: usize,
+}
+
+impl Adsr {
+    /// A short, snappy default envelope scaled to `sample_rate`.
+    pub fn default_for(sample_rate: u32) -> Adsr {
+        Adsr {
+            attack: (sample_rate as f64 * 0.01) as usize,
+            decay: (sample_rate as f64 * 0.05) as usize,
+            sustain: 0.7,
+            release: (sample_rate as f64 * 0.05) as usize,
+        }
+    }
+
+    /// Scales attack/decay/release down proportionally so they never
+    /// overrun a note shorter than `attack + decay + release`.
+    fn fit(&self, total_samples: usize) -> (usize, usize, usize) {
+        let total = self.attack + self.decay + self.release;
+        if total == 0 || total <= total_samples {
+            (self.attack, self.decay, self.release)
+        } else {
+            let scale = total_samples as f64 / total as f64;
+            (
+                (self.attack as f64 * scale) as usize,
+                (self.decay as f64 * scale) as usize,
+                (self.release as f64 * scale) as usize,
+            )
+        }
+    }
+
+    /// The envelope multiplier at sample index `i` of a note `total_samples`
+    /// samples long.
+    fn amplitude(&self, i: usize, total_samples: usize) -> f64 {
+        let (attack, decay, release) = self.fit(total_samples);
+        let sustain_start = attack + decay;
+        let release_start = total_samples.saturating_sub(release);
+
+        if i < attack {
+            if attack == 0 { 1.0 } else { i as f64 / attack as f64 }
+        } else if i < sustain_start {
+            if decay == 0 {
+                self.sustain
+            } else {
+                let t = (i - attack) as f64 / decay as f64;
+                1.0 + t * (self.sustain - 1.0)
+            }
+        } else if i < release_start {
+            self.sustain
+        } else if release == 0 {
+            0.0
+        } else {
+            let t = (i - release_start) as f64 / release as f64;
+            self.sustain * (1.0 - t)
+        }
+    }
+}
+
+/// Something that can render a single note to samples.
+pub trait Oscillator {
+    /// Renders `duration_secs` worth of samples at `pitch_hz`, scaled by
+    /// `velocity` (0.0-1.0) and shaped by the instrument's envelope.
+    fn play(&self, pitch_hz: f64, sample_rate: u32, duration_secs: f64, velocity: f64) -> Vec<f32>;
+}
+
+/// A sine wave oscillator.
+#[derive(Debug, Clone, Copy)]
+pub struct Sinus {
+    pub envelope: Adsr,
+}
+
+impl Oscillator for Sinus {
+    fn play(&self, pitch_hz: f64, sample_rate: u32, duration_secs: f64, velocity: f64) -> Vec<f32> {
+        let total_samples = (duration_secs * sample_rate as f64).floor() as usize;
+        let step = 2.0 * PI * pitch_hz / sample_rate as f64;
+        let mut phase: f64 = 0.0;
+        (0..total_samples)
+            .map(|i| {
+                let amplitude = self.envelope.amplitude(i, total_samples) * velocity;
+                let sample = (phase.sin() * amplitude) as f32;
+                phase += step;
+                sample
+            })
+            .collect()
+    }
+}
+
+/// A square wave oscillator.
+#[derive(Debug, Clone, Copy)]
+pub struct Square {
+    pub envelope: Adsr,
+}
+
+impl Oscillator for Square {
+    fn play(&self, pitch_hz: f64, sample_rate: u32, duration_secs: f64, velocity: f64) -> Vec<f32> {
+        let total_samples = (duration_secs * sample_rate as f64).floor() as usize;
+        let step = 2.0 * PI * pitch_hz / sample_rate as f64;
+        let mut phase: f64 = 0.0;
+        (0..total_samples)
+            .map(|i| {
+                let amplitude = self.envelope.amplitude(i, total_samples) * velocity;
+                let sign = if phase.sin() >= 0.0 { 1.0 } else { -1.0 };
+                let sample = (sign * amplitude) as f32;
+                phase += step;
+                sample
+            })
+            .collect()
+    }
+}
+
+/// Renders every event of `performance` through `instrument` and sums the
+/// result into a single sample buffer, normalising afterwards to avoid
+/// clipping.
+pub fn render(performance: &Performance, instrument: &dyn Oscillator, sample_rate: u32) -> Vec<f32> {
+    let mut buffer: Vec<f32> = Vec::new();
+    for event in performance {
+        let start_sample = (ticks_to_secs(event.start_tick) * sample_rate as f64).round() as usize;
+        let duration_secs = ticks_to_secs(event.duration);
+        let velocity = event.velocity as f64 / 127.0;
+        let samples = instrument.play(key_to_frequency(event.pitch), sample_rate, duration_secs, velocity);
+
+        let end_sample = start_sample + samples.len();
+        if buffer.len() < end_sample {
+            buffer.resize(end_sample, 0.0);
+        }
+        for (i, sample) in samples.into_iter().enumerate() {
+            buffer[start_sample + i] += sample;
+        }
+    }
+    normalize(&mut buffer);
+    buffer
+}
+
+/// Scales the buffer down so its peak sample sits at 1.0 when it would
+/// otherwise clip; a buffer whose peak is already at most 1.0 (silence
+/// included) is left untouched.
+fn normalize(buffer: &mut [f32]) {
+    let peak = buffer.iter().fold(0.0f32, |max, &sample| max.max(sample.abs()));
+    if peak > 1.0 {
+        for sample in buffer.iter_mut() {
+            *sample /= peak;
+        }
+    }
+}
+
+/// Writes `samples` out as a mono, 16-bit PCM WAV file.
+pub fn write_wav(path: &str, samples: &[f32], sample_rate: u32) -> io::Result<()> {
+    const BITS_PER_SAMPLE: u16 = 16;
+    const CHANNELS: u16 = 1;
+
+    let block_align = CHANNELS * (BITS_PER_SAMPLE / 8);
+    let byte_rate = sample_rate * block_align as u32;
+    let data_size = samples.len() as u32 * block_align as u32;
+
+    let mut file = File::create(path)?;
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_size).to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+
+    file.write_all(b"fmt ")?;
+    file.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    file.write_all(&1u16.to_le_bytes())?; // PCM
+    file.write_all(&CHANNELS.to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&byte_rate.to_le_bytes())?;
+    file.write_all(&block_align.to_le_bytes())?;
+    file.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+    for &sample in samples {
+        let clamped = sample.clamp(-1.0, 1.0);
+        let value = (clamped * i16::MAX as f32) as i16;
+        file.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amplitude_hits_one_at_the_end_of_attack() {
+        let adsr = Adsr { attack: 10, decay: 5, sustain: 0.5, release: 10 };
+        assert_eq!(adsr.amplitude(10, 100), 1.0);
+    }
+
+    #[test]
+    fn amplitude_holds_sustain_level_between_decay_and_release() {
+        let adsr = Adsr { attack: 10, decay: 5, sustain: 0.5, release: 10 };
+        assert_eq!(adsr.amplitude(50, 100), 0.5);
+    }
+
+    #[test]
+    fn amplitude_reaches_zero_at_the_end_of_release() {
+        let adsr = Adsr { attack: 10, decay: 5, sustain: 0.5, release: 10 };
+        assert_eq!(adsr.amplitude(99, 100), 0.5 * (1.0 - 9.0 / 10.0));
+        assert_eq!(adsr.amplitude(100, 100), 0.0);
+    }
+}
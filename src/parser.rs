@@ -0,0 +1,225 @@
+//! A small text DSL for writing `Sequential`/`Parallel` trees without
+//! nesting Rust macros by hand.
+//!
+//! Tokens are whitespace- (and `|`-bar-) separated:
+//!   - a note: `c4:1/4`, `f#5:3/16`, solfège spellings like `do4:1/4` too
+//!   - a rest: `r:1/2`
+//!   - a chord, parsed as `Parallel`: `[c4 e4 g4]:1/2`
+//!   - an octave change, persists until the next one: `o5`
+//!   - a default-duration change, persists until the next one: `l1/8`
+//!
+//! Octave and duration are both optional on a note/chord; whichever was
+//! last set (or the defaults, octave 4 and a quarter note) carries over.
+
+use std::fmt;
+
+use crate::duration::Dur;
+use crate::{Note, NoteClass, Parallel, Pause, Sequential};
+
+/// A parse failure, tagged with the byte offset of the offending token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "parse error at byte {}: {}", self.position, self.message)
+    }
+}
+
+fn error(position: usize, message: impl Into<String>) -> ParseError {
+    ParseError { position, message: message.into() }
+}
+
+struct State {
+    octave: u32,
+    duration: Dur,
+}
+
+/// Parses a whole piece into a `Sequential` of its top-level tokens.
+pub fn parse(input: &str) -> Result<Sequential, ParseError> {
+    let mut state = State { octave: 4, duration: Dur::new(1, 4) };
+    let mut sequence = Sequential::default();
+
+    for (offset, token) in tokenize(input) {
+        if let Some(octave) = parse_octave_directive(token) {
+            state.octave = octave.ok_or_else(|| error(offset, format!("invalid octave directive: {:?}", token)))?;
+            continue;
+        }
+        if let Some(duration) = parse_duration_directive(token) {
+            state.duration = duration.map_err(|e| error(offset, e))?;
+            continue;
+        }
+        if token.starts_with('[') {
+            sequence.elements.push(Box::new(parse_chord(token, offset, &state)?));
+            continue;
+        }
+        if token == "r" || token.starts_with("r:") {
+            sequence.elements.push(Box::new(parse_rest(token, offset, &state)?));
+            continue;
+        }
+        sequence.elements.push(Box::new(parse_note(token, offset, &state)?));
+    }
+
+    Ok(sequence)
+}
+
+/// Splits on whitespace and bar lines (`|`), tracking each token's starting
+/// byte offset for error reporting. Whitespace inside a `[...]` chord does
+/// not split the chord into separate tokens.
+fn tokenize(input: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut in_chord = false;
+    for (i, c) in input.char_indices() {
+        if c == '[' {
+            in_chord = true;
+        } else if c == ']' {
+            in_chord = false;
+        }
+        let is_separator = !in_chord && (c.is_whitespace() || c == '|');
+        match (is_separator, start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                tokens.push((s, &input[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, &input[s..]));
+    }
+    tokens
+}
+
+/// `o5` sets the default octave. Returns `None` if `token` isn't an octave
+/// directive, `Some(None)` if it looks like one but fails to parse.
+fn parse_octave_directive(token: &str) -> Option<Option<u32>> {
+    let rest = token.strip_prefix('o')?;
+    if rest.is_empty() || !rest.chars().all(|c| c.is_ascii_digit()) {
+        return Some(None);
+    }
+    Some(rest.parse().ok())
+}
+
+/// `l1/8` sets the default duration. Returns `None` if `token` isn't a
+/// duration directive.
+fn parse_duration_directive(token: &str) -> Option<Result<Dur, String>> {
+    let rest = token.strip_prefix('l')?;
+    if rest.is_empty() || !rest.chars().next().unwrap().is_ascii_digit() {
+        return None;
+    }
+    Some(parse_fraction(rest))
+}
+
+/// Parses `"3/16"` or a bare integer like `"2"` into a `Dur`.
+fn parse_fraction(text: &str) -> Result<Dur, String> {
+    match text.split_once('/') {
+        Some((num, den)) => {
+            let num: u32 = num.parse().map_err(|_| format!("invalid duration numerator: {:?}", text))?;
+            let den: u32 = den.parse().map_err(|_| format!("invalid duration denominator: {:?}", text))?;
+            if den == 0 {
+                return Err(format!("invalid duration denominator: {:?}", text));
+            }
+            Ok(Dur::new(num, den))
+        }
+        None => {
+            let num: u32 = text.parse().map_err(|_| format!("invalid duration: {:?}", text))?;
+            Ok(Dur::new(num, 1))
+        }
+    }
+}
+
+/// Splits `"f#5"` into its note-name part (`"f#"`) and trailing octave
+/// digits (`"5"`), if any.
+fn split_name_and_octave(text: &str) -> (&str, Option<&str>) {
+    let digit_start = text.find(|c: char| c.is_ascii_digit());
+    match digit_start {
+        Some(i) => (&text[..i], Some(&text[i..])),
+        None => (text, None),
+    }
+}
+
+/// Parses `"c4:1/4"`-style note body into `(name, octave override, duration override)`.
+fn parse_note_body(body: &str) -> (&str, Option<&str>, Option<&str>) {
+    match body.split_once(':') {
+        Some((head, duration)) => {
+            let (name, octave) = split_name_and_octave(head);
+            (name, octave, Some(duration))
+        }
+        None => {
+            let (name, octave) = split_name_and_octave(body);
+            (name, octave, None)
+        }
+    }
+}
+
+fn parse_note(token: &str, offset: usize, state: &State) -> Result<Note, ParseError> {
+    let (name, octave, duration) = parse_note_body(token);
+    let class = NoteClass::parse(name).ok_or_else(|| error(offset, format!("unknown note name: {:?}", name)))?;
+    let octave = match octave {
+        Some(digits) => digits.parse().map_err(|_| error(offset, format!("invalid octave: {:?}", digits)))?,
+        None => state.octave,
+    };
+    let duration = match duration {
+        Some(text) => parse_fraction(text).map_err(|e| error(offset, e))?,
+        None => state.duration,
+    };
+    Ok(Note::new(class, octave).duration(duration))
+}
+
+fn parse_rest(token: &str, offset: usize, state: &State) -> Result<Pause, ParseError> {
+    let duration = match token.split_once(':') {
+        Some((_, text)) => parse_fraction(text).map_err(|e| error(offset, e))?,
+        None => state.duration,
+    };
+    Ok(Pause::new(duration))
+}
+
+fn parse_chord(token: &str, offset: usize, state: &State) -> Result<Parallel, ParseError> {
+    let close = token.find(']').ok_or_else(|| error(offset, "unterminated chord: missing ']'"))?;
+    let inner = &token[1..close];
+    let duration = match token[close + 1..].strip_prefix(':') {
+        Some(text) => parse_fraction(text).map_err(|e| error(offset, e))?,
+        None => state.duration,
+    };
+
+    let mut chord = Parallel::default();
+    for note_text in inner.split_whitespace() {
+        let (name, octave) = split_name_and_octave(note_text);
+        let class = NoteClass::parse(name).ok_or_else(|| error(offset, format!("unknown note name: {:?}", name)))?;
+        let octave = match octave {
+            Some(digits) => digits.parse().map_err(|_| error(offset, format!("invalid octave: {:?}", digits)))?,
+            None => state.octave,
+        };
+        chord.elements.push(Box::new(Note::new(class, octave).duration(duration)));
+    }
+    Ok(chord)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_garbage_note_names() {
+        assert!(parse("bogus_token").is_err());
+        assert!(parse("cats").is_err());
+        assert!(parse("fable").is_err());
+        assert!(parse("garbage").is_err());
+    }
+
+    #[test]
+    fn rejects_zero_denominator_durations() {
+        assert!(parse("c4:1/0").is_err());
+        assert!(parse("l1/0").is_err());
+    }
+
+    #[test]
+    fn parses_a_simple_note() {
+        assert!(parse("c4:1/4").is_ok());
+    }
+}
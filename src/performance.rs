@@ -0,0 +1,26 @@
+//! Flattens a `MusicElement` tree into a single, absolute-time list of
+//! events. This is the common input every renderer (MIDI export, audio
+//! synthesis, analysis, ...) should consume instead of re-walking the
+//! boxed trait objects itself.
+
+use crate::Instrument;
+
+/// A single sounding note, with its timing already resolved to absolute
+/// ticks.
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    /// Absolute start time, in MIDI ticks.
+    pub start_tick: f32,
+    /// How long the note sounds, in MIDI ticks.
+    pub duration: f32,
+    /// MIDI pitch, as produced by `Note::to_midi`.
+    pub pitch: u32,
+    /// The instrument this note should sound on; resolved to an actual
+    /// MIDI channel number by `UserPatchMap` at export time.
+    pub instrument: Instrument,
+    pub velocity: u8,
+}
+
+/// A flattened, absolute-time performance: just a bag of events in no
+/// particular order.
+pub type Performance = Vec<Event>;
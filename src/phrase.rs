@@ -0,0 +1,176 @@
+//! Phrase attributes: dynamics and articulation applied to a whole subtree.
+//!
+//! A `Phrase` wraps any `MusicElement` and carries one `PhraseAttribute`.
+//! The attribute doesn't touch the wrapped element's structural
+//! `duration()` --- nesting a `Phrase` inside `Sequential`/`Parallel` still
+//! composes exactly like the element it wraps. Instead, the attribute is
+//! resolved during `perform`, rewriting the velocity/duration of whichever
+//! `Event`s the subtree produces.
+
+use crate::duration::Dur;
+use crate::instrument::Instrument;
+use crate::performance::{Event, Performance};
+use crate::{MusicElement, MIDI_TEMPO};
+
+/// Named loudness levels, in ascending order, mapped onto MIDI velocity.
+/// Spelled the way dynamics markings are written in a score, not as
+/// acronyms, so `upper_case_acronyms` doesn't apply here.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loudness {
+    PPP, PP, P, MP, MF, F, FF, FFF,
+}
+
+impl Loudness {
+    fn velocity(self) -> u8 {
+        match self {
+            Loudness::PPP => 16,
+            Loudness::PP => 32,
+            Loudness::P => 48,
+            Loudness::MP => 64,
+            Loudness::MF => 80,
+            Loudness::F => 96,
+            Loudness::FF => 112,
+            Loudness::FFF => 127,
+        }
+    }
+}
+
+/// A dynamics or articulation marking applied to an entire subtree.
+#[derive(Debug, Clone, Copy)]
+pub enum PhraseAttribute {
+    /// Sets every contained event to a fixed velocity.
+    Dynamics(Loudness),
+    /// Ramps velocity up linearly across the subtree, ending at `factor`
+    /// times the note's own velocity.
+    Crescendo(f32),
+    /// Ramps velocity down linearly across the subtree, ending at `factor`
+    /// times the note's own velocity.
+    Diminuendo(f32),
+    /// Shortens every contained note's sounding duration to `fraction` of
+    /// its slot, without moving its start time.
+    Staccato(f32),
+    /// Scales the velocity of the first note in the subtree by `factor`.
+    Accent(f32),
+}
+
+fn scale_velocity(velocity: u8, factor: f32) -> u8 {
+    (velocity as f32 * factor).round().clamp(0.0, 127.0) as u8
+}
+
+impl PhraseAttribute {
+    /// Rewrites `events`, which together span `[start, start + span)`.
+    fn apply(self, events: &mut [Event], start: f32, span: f32) {
+        match self {
+            PhraseAttribute::Dynamics(loudness) => {
+                let velocity = loudness.velocity();
+                for event in events.iter_mut() {
+                    event.velocity = velocity;
+                }
+            }
+            PhraseAttribute::Crescendo(factor) => {
+                for event in events.iter_mut() {
+                    let t = if span > 0.0 { (event.start_tick - start) / span } else { 0.0 };
+                    event.velocity = scale_velocity(event.velocity, 1.0 + t * (factor - 1.0));
+                }
+            }
+            PhraseAttribute::Diminuendo(factor) => {
+                for event in events.iter_mut() {
+                    let t = if span > 0.0 { (event.start_tick - start) / span } else { 0.0 };
+                    event.velocity = scale_velocity(event.velocity, 1.0 - t * (1.0 - factor));
+                }
+            }
+            PhraseAttribute::Staccato(fraction) => {
+                for event in events.iter_mut() {
+                    event.duration *= fraction;
+                }
+            }
+            PhraseAttribute::Accent(factor) => {
+                if let Some(first) =
+                    events.iter_mut().min_by(|a, b| a.start_tick.partial_cmp(&b.start_tick).unwrap())
+                {
+                    first.velocity = scale_velocity(first.velocity, factor);
+                }
+            }
+        }
+    }
+}
+
+/// Wraps a `MusicElement` subtree with a single `PhraseAttribute`. Nest
+/// several `Phrase`s to apply more than one attribute at once.
+#[derive(Debug, Clone)]
+pub struct Phrase {
+    attribute: PhraseAttribute,
+    element: Box<MusicElement>,
+}
+
+impl Phrase {
+    pub fn new(attribute: PhraseAttribute, element: Box<MusicElement>) -> Phrase {
+        Phrase { attribute, element }
+    }
+}
+
+impl MusicElement for Phrase {
+    fn duration(&self) -> Dur {
+        self.element.duration()
+    }
+
+    fn set_channel(&mut self, channel: Instrument) {
+        self.element.set_channel(channel);
+    }
+
+    fn perform(&self, start: f32) -> Performance {
+        let mut events = self.element.perform(start);
+        let span = self.duration().to_ticks(MIDI_TEMPO) as f32;
+        self.attribute.apply(&mut events, start, span);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(start_tick: f32, duration: f32, velocity: u8) -> Event {
+        Event { start_tick, duration, pitch: 60, instrument: Instrument::AcousticGrandPiano, velocity }
+    }
+
+    #[test]
+    fn dynamics_sets_every_event_to_the_same_velocity() {
+        let mut events = vec![event(0.0, 10.0, 10), event(10.0, 10.0, 20)];
+        PhraseAttribute::Dynamics(Loudness::FF).apply(&mut events, 0.0, 20.0);
+        assert!(events.iter().all(|e| e.velocity == Loudness::FF.velocity()));
+    }
+
+    #[test]
+    fn crescendo_ramps_from_the_original_velocity_up_to_factor() {
+        let mut events = vec![event(0.0, 0.0, 100), event(20.0, 0.0, 100)];
+        PhraseAttribute::Crescendo(2.0).apply(&mut events, 0.0, 20.0);
+        assert_eq!(events[0].velocity, 100);
+        assert_eq!(events[1].velocity, 127); // scale_velocity clamps 200 down to the MIDI max
+    }
+
+    #[test]
+    fn diminuendo_ramps_from_the_original_velocity_down_to_factor() {
+        let mut events = vec![event(0.0, 0.0, 100), event(20.0, 0.0, 100)];
+        PhraseAttribute::Diminuendo(0.5).apply(&mut events, 0.0, 20.0);
+        assert_eq!(events[0].velocity, 100);
+        assert_eq!(events[1].velocity, 50);
+    }
+
+    #[test]
+    fn staccato_shortens_duration_without_moving_the_start() {
+        let mut events = vec![event(0.0, 10.0, 100)];
+        PhraseAttribute::Staccato(0.5).apply(&mut events, 0.0, 10.0);
+        assert_eq!(events[0].start_tick, 0.0);
+        assert_eq!(events[0].duration, 5.0);
+    }
+
+    #[test]
+    fn accent_only_scales_the_earliest_event() {
+        let mut events = vec![event(0.0, 10.0, 50), event(10.0, 10.0, 50)];
+        PhraseAttribute::Accent(2.0).apply(&mut events, 0.0, 20.0);
+        assert_eq!(events[0].velocity, 100);
+        assert_eq!(events[1].velocity, 50);
+    }
+}